@@ -1,7 +1,10 @@
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use image::{DynamicImage, ImageError, Rgba};
-use indicatif::{ProgressBar, ProgressStyle};
+use image::{DynamicImage, Frame, ImageError, Rgba, RgbaImage};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rayon::prelude::*;
 use structopt::{
     clap::{_clap_count_exprs, arg_enum},
     StructOpt,
@@ -36,6 +39,141 @@ fn pixel_hue(pixel: &Rgba<u8>) -> u8 {
     }
 }
 
+// https://bottosson.github.io/posts/oklab/
+fn linearize(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn pixel_oklab(pixel: &Rgba<u8>) -> (f32, f32, f32) {
+    let Rgba { data, .. } = pixel;
+
+    let r = linearize(data[0]);
+    let g = linearize(data[1]);
+    let b = linearize(data[2]);
+
+    let l = 0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b;
+    let m = 0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b;
+    let s = 0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b;
+
+    let l_ = l.cbrt();
+    let m_ = m.cbrt();
+    let s_ = s.cbrt();
+
+    let big_l = 0.2104542553 * l_ + 0.7936177850 * m_ - 0.0040720468 * s_;
+    let a = 1.9779984951 * l_ - 2.4285922050 * m_ + 0.4505937099 * s_;
+    let b = 0.0259040371 * l_ + 0.7827717662 * m_ - 0.8086757660 * s_;
+
+    (big_l, a, b)
+}
+
+fn pixel_oklab_lightness(pixel: &Rgba<u8>) -> u8 {
+    let (l, _, _) = pixel_oklab(pixel);
+    (l.max(0.0).min(1.0) * 255.0) as u8
+}
+
+fn pixel_oklab_chroma(pixel: &Rgba<u8>) -> u8 {
+    let (_, a, b) = pixel_oklab(pixel);
+    ((a * a + b * b).sqrt() * 255.0).min(255.0) as u8
+}
+
+// CIE 1931 linear RGB -> XYZ (D65), then the standard L* lightness curve.
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+
+    if t > DELTA.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+fn pixel_cielab_l(pixel: &Rgba<u8>) -> u8 {
+    let Rgba { data, .. } = pixel;
+
+    let r = linearize(data[0]);
+    let g = linearize(data[1]);
+    let b = linearize(data[2]);
+
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let l = 116.0 * lab_f(y) - 16.0;
+
+    (l / 100.0 * 255.0).max(0.0).min(255.0) as u8
+}
+
+// Interleave the bits of R, G, B into a 24-bit Morton (Z-order) code: bit i
+// of R lands at position 3i, G at 3i+1, B at 3i+2.
+fn pixel_morton(pixel: &Rgba<u8>) -> u32 {
+    let Rgba { data, .. } = pixel;
+    let mut code = 0u32;
+
+    for i in 0..8 {
+        code |= (u32::from(data[0]) >> i & 1) << (3 * i);
+        code |= (u32::from(data[1]) >> i & 1) << (3 * i + 1);
+        code |= (u32::from(data[2]) >> i & 1) << (3 * i + 2);
+    }
+
+    code
+}
+
+// Skilling's transpose algorithm ("Programming the Hilbert curve", 2004).
+fn hilbert_distance(mut coords: [u32; 3], bits: u32) -> u32 {
+    let n = coords.len();
+
+    let mut q = 1 << (bits - 1);
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..n {
+            if coords[i] & q != 0 {
+                coords[0] ^= p;
+            } else {
+                let t = (coords[0] ^ coords[i]) & p;
+                coords[0] ^= t;
+                coords[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    for i in 1..n {
+        coords[i] ^= coords[i - 1];
+    }
+
+    let mut t = 0;
+    let mut q = 1 << (bits - 1);
+    while q > 1 {
+        if coords[n - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for c in coords.iter_mut() {
+        *c ^= t;
+    }
+
+    let mut dist = 0u32;
+    for i in 0..bits {
+        for c in coords.iter() {
+            dist = dist << 1 | (c >> (bits - 1 - i) & 1);
+        }
+    }
+
+    dist
+}
+
+fn pixel_hilbert(pixel: &Rgba<u8>) -> u32 {
+    let Rgba { data, .. } = pixel;
+    hilbert_distance(
+        [u32::from(data[0]), u32::from(data[1]), u32::from(data[2])],
+        8,
+    )
+}
+
 arg_enum! {
     enum SortHeuristic {
         Luma,
@@ -49,34 +187,48 @@ arg_enum! {
         Red,
         Blue,
         Green,
+        Oklab,
+        OklabChroma,
+        CieLabL,
+        Morton,
+        Hilbert,
     }
 }
 
 impl SortHeuristic {
-    fn func(&self) -> Box<Fn(&Rgba<u8>) -> u8> {
+    fn func(&self) -> Box<dyn Fn(&Rgba<u8>) -> u32> {
         match self {
-            SortHeuristic::Red => Box::new(|Rgba { data, .. }| data[0]),
-            SortHeuristic::Green => Box::new(|Rgba { data, .. }| data[1]),
-            SortHeuristic::Blue => Box::new(|Rgba { data, .. }| data[2]),
-            SortHeuristic::Max => Box::new(pixel_max),
-            SortHeuristic::Min => Box::new(pixel_min),
-            SortHeuristic::Chroma => Box::new(pixel_chroma),
-            SortHeuristic::Hue => Box::new(pixel_hue),
-            SortHeuristic::Saturation => Box::new(|p| match pixel_max(p) {
-                0 => 0,
-                v => pixel_chroma(p) / v,
+            SortHeuristic::Red => Box::new(|Rgba { data, .. }| u32::from(data[0])),
+            SortHeuristic::Green => Box::new(|Rgba { data, .. }| u32::from(data[1])),
+            SortHeuristic::Blue => Box::new(|Rgba { data, .. }| u32::from(data[2])),
+            SortHeuristic::Max => Box::new(|p| u32::from(pixel_max(p))),
+            SortHeuristic::Min => Box::new(|p| u32::from(pixel_min(p))),
+            SortHeuristic::Chroma => Box::new(|p| u32::from(pixel_chroma(p))),
+            SortHeuristic::Hue => Box::new(|p| u32::from(pixel_hue(p))),
+            SortHeuristic::Saturation => Box::new(|p| {
+                u32::from(match pixel_max(p) {
+                    0 => 0,
+                    v => pixel_chroma(p) / v,
+                })
             }),
-            SortHeuristic::Value => Box::new(pixel_max),
+            SortHeuristic::Value => Box::new(|p| u32::from(pixel_max(p))),
             SortHeuristic::Brightness => Box::new(|Rgba { data, .. }| {
-                data[0] / 3
-                    + data[1] / 3
-                    + data[2] / 3
-                    + (data[0] % 3 + data[1] % 3 + data[2] % 3) / 3
+                u32::from(
+                    data[0] / 3
+                        + data[1] / 3
+                        + data[2] / 3
+                        + (data[0] % 3 + data[1] % 3 + data[2] % 3) / 3,
+                )
             }),
             SortHeuristic::Luma => Box::new(|Rgba { data, .. }| {
                 // https://stackoverflow.com/a/596241
-                ((data[0] as u16 * 2 + data[1] as u16 + data[2] as u16 * 4) >> 3) as u8
+                (data[0] as u16 * 2 + data[1] as u16 + data[2] as u16 * 4) as u32 >> 3
             }),
+            SortHeuristic::Oklab => Box::new(|p| u32::from(pixel_oklab_lightness(p))),
+            SortHeuristic::OklabChroma => Box::new(|p| u32::from(pixel_oklab_chroma(p))),
+            SortHeuristic::CieLabL => Box::new(|p| u32::from(pixel_cielab_l(p))),
+            SortHeuristic::Morton => Box::new(pixel_morton),
+            SortHeuristic::Hilbert => Box::new(pixel_hilbert),
         }
     }
 }
@@ -86,12 +238,24 @@ impl SortHeuristic {
 #[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
 #[structopt(rename_all = "kebab-case")]
 struct Cli {
-    /// Input file
-    #[structopt(parse(try_from_str))]
-    file: PathBuf,
-    /// Output file
+    /// Input file(s), accepting glob patterns and directories
+    #[structopt(parse(try_from_str), required = true, min_values = 1)]
+    file: Vec<PathBuf>,
+    /// Output file (only valid with a single input file)
     #[structopt(short, parse(try_from_str))]
     output: Option<PathBuf>,
+    /// Directory to save output files into, named after each input file and
+    /// prefixed with its parent directory (e.g. `in/a.png` -> `in_a.png`)
+    #[structopt(long, parse(try_from_str))]
+    output_dir: Option<PathBuf>,
+    /// Reduce the image to N colors (median-cut quantization) before sorting
+    #[structopt(long)]
+    quantize: Option<usize>,
+    /// Also emit the sort as an animation: one frame after every height/N
+    /// sorted rows (rounded down, at least one row) plus a frame of the final
+    /// image (a GIF next to the input, or numbered PNGs with --output-dir)
+    #[structopt(long)]
+    animate: Option<usize>,
     /// Minimum value to sort
     #[structopt(short, default_value = "0")]
     minimum: u8,
@@ -123,107 +287,416 @@ struct Cli {
     mask_alpha: bool,
 }
 
-fn main() -> Result<(), ImageError> {
-    let cli = Cli::from_args();
+// Median-cut color quantization, as used to build a reduced palette before
+// posterizing an image. Operates on RGB only; alpha passes through.
+
+fn channel_range(colors: &[([u8; 3], u64)]) -> (usize, u8) {
+    let mut mins = [255u8; 3];
+    let mut maxs = [0u8; 3];
+
+    for (c, _) in colors {
+        for i in 0..3 {
+            mins[i] = mins[i].min(c[i]);
+            maxs[i] = maxs[i].max(c[i]);
+        }
+    }
+
+    let ranges = [maxs[0] - mins[0], maxs[1] - mins[1], maxs[2] - mins[2]];
+    let (axis, &range) = ranges.iter().enumerate().max_by_key(|&(_, r)| r).unwrap();
+
+    (axis, range)
+}
+
+fn average_color(colors: &[([u8; 3], u64)]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    let mut total = 0u64;
+
+    for (c, count) in colors {
+        for i in 0..3 {
+            sum[i] += u64::from(c[i]) * count;
+        }
+        total += count;
+    }
+
+    if total == 0 {
+        return [0, 0, 0];
+    }
+
+    [
+        (sum[0] / total) as u8,
+        (sum[1] / total) as u8,
+        (sum[2] / total) as u8,
+    ]
+}
+
+fn median_cut_palette(colors: Vec<([u8; 3], u64)>, n: usize) -> Vec<[u8; 3]> {
+    let mut boxes = vec![colors];
+
+    while boxes.len() < n {
+        let split_idx = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .max_by_key(|(_, b)| channel_range(b).1)
+            .map(|(i, _)| i);
+
+        let idx = match split_idx {
+            Some(i) => i,
+            None => break,
+        };
+
+        let mut lo = boxes.remove(idx);
+        let (axis, _) = channel_range(&lo);
+        lo.sort_unstable_by_key(|(c, _)| c[axis]);
+
+        let hi = lo.split_off(lo.len() / 2);
+        boxes.push(lo);
+        boxes.push(hi);
+    }
+
+    boxes.iter().map(|b| average_color(b)).collect()
+}
+
+fn nearest_palette_index(color: [u8; 3], palette: &[[u8; 3]]) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, p)| {
+            let dr = i32::from(p[0]) - i32::from(color[0]);
+            let dg = i32::from(p[1]) - i32::from(color[1]);
+            let db = i32::from(p[2]) - i32::from(color[2]);
+            dr * dr + dg * dg + db * db
+        })
+        .map(|(i, _)| i)
+        .unwrap()
+}
+
+fn quantize(rgba: &RgbaImage, n: usize) -> RgbaImage {
+    let mut histogram: HashMap<[u8; 3], u64> = HashMap::new();
+    for Rgba { data, .. } in rgba.pixels() {
+        *histogram.entry([data[0], data[1], data[2]]).or_insert(0) += 1;
+    }
+
+    let palette = median_cut_palette(histogram.into_iter().collect(), n);
+    let mut nearest_cache: HashMap<[u8; 3], usize> = HashMap::new();
+
+    let (w, h) = rgba.dimensions();
+    let mut out = RgbaImage::new(w, h);
+
+    for (x, y, Rgba { data, .. }) in rgba.enumerate_pixels() {
+        let key = [data[0], data[1], data[2]];
+        let idx = *nearest_cache
+            .entry(key)
+            .or_insert_with(|| nearest_palette_index(key, &palette));
+        let c = palette[idx];
+        out.put_pixel(
+            x,
+            y,
+            Rgba {
+                data: [c[0], c[1], c[2], data[3]],
+            },
+        );
+    }
+
+    out
+}
 
-    eprintln!("Opening image at {:?}", cli.file);
-    let mut img = image::open(&cli.file)?;
+// Expand directories and glob patterns (e.g. `*.png`) into a flat, sorted
+// list of concrete input files.
+fn expand_inputs(patterns: &[PathBuf]) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+
+    for pattern in patterns {
+        if pattern.is_dir() {
+            match std::fs::read_dir(pattern) {
+                Ok(entries) => out.extend(entries.filter_map(|e| match e {
+                    Ok(e) => Some(e.path()),
+                    Err(err) => {
+                        eprintln!("Warning: couldn't read an entry of {:?}: {}", pattern, err);
+                        None
+                    }
+                })),
+                Err(err) => eprintln!("Warning: couldn't read directory {:?}: {}", pattern, err),
+            }
+            continue;
+        }
+
+        let pattern_str = pattern.to_string_lossy();
+        if pattern_str.contains('*') || pattern_str.contains('?') || pattern_str.contains('[') {
+            match glob::glob(&pattern_str) {
+                Ok(paths) => out.extend(paths.filter_map(|p| match p {
+                    Ok(p) => Some(p),
+                    Err(err) => {
+                        eprintln!("Warning: couldn't read a match of {:?}: {}", pattern, err);
+                        None
+                    }
+                })),
+                Err(err) => eprintln!("Warning: invalid glob pattern {:?}: {}", pattern, err),
+            }
+            continue;
+        }
+
+        out.push(pattern.clone());
+    }
+
+    out.sort();
+    out
+}
+
+// Namespace prefix built from an input's parent directory, so that inputs
+// with the same basename in different directories (a common case once
+// glob/directory expansion is in play) don't collide once everything lands
+// in the same --output-dir.
+fn namespace_prefix(file_in: &Path) -> String {
+    file_in
+        .parent()
+        .map(|p| p.to_string_lossy().replace('/', "_").replace('\\', "_"))
+        .filter(|s| !s.is_empty())
+        .map(|s| format!("{}_", s))
+        .unwrap_or_default()
+}
+
+fn check_unused(path: PathBuf, used: &mut HashSet<PathBuf>) -> PathBuf {
+    if !used.insert(path.clone()) {
+        panic!(
+            "Output path {:?} would be overwritten by another input file",
+            path
+        );
+    }
+
+    path
+}
+
+fn output_path_for(
+    file_in: &Path,
+    cli: &Cli,
+    single_input: bool,
+    used: &mut HashSet<PathBuf>,
+) -> PathBuf {
+    if single_input {
+        if let Some(p) = &cli.output {
+            return p.clone();
+        }
+    }
+
+    match (file_in.file_stem(), file_in.extension()) {
+        (None, _) | (_, None) => panic!("Invalid filename"),
+        (Some(b), Some(e)) => {
+            if let Some(dir) = &cli.output_dir {
+                let mut fname = namespace_prefix(file_in);
+                fname.push_str(&b.to_string_lossy());
+                fname.push('.');
+                fname.push_str(&e.to_string_lossy());
+                let mut pth = dir.clone();
+                pth.push(fname);
+                check_unused(pth, used)
+            } else {
+                let mut fname = b.to_owned();
+                fname.push("_1.");
+                fname.push(e);
+                let mut pth = file_in.parent().map(Path::to_owned).unwrap_or_default();
+                pth.push(fname);
+                check_unused(pth, used)
+            }
+        }
+    }
+}
+
+// Write out the captured frames of a `--animate` run: an animated GIF next
+// to the input file, or numbered PNGs into `--output-dir`.
+fn save_animation(
+    frames: Vec<RgbaImage>,
+    file_in: &Path,
+    cli: &Cli,
+    used: &mut HashSet<PathBuf>,
+) -> Result<(), ImageError> {
+    if let Some(dir) = &cli.output_dir {
+        let prefix = namespace_prefix(file_in);
+        let stem = file_in.file_stem().unwrap_or_default().to_string_lossy();
+
+        for (i, frame) in frames.into_iter().enumerate() {
+            let mut path = dir.clone();
+            path.push(format!("{}{}_{:04}.png", prefix, stem, i));
+            let path = check_unused(path, used);
+            DynamicImage::ImageRgba8(frame).save(path)?;
+        }
+
+        Ok(())
+    } else {
+        let mut path = file_in.to_path_buf();
+        path.set_extension("gif");
+        let path = check_unused(path, used);
+
+        let file = std::fs::File::create(path).map_err(ImageError::IoError)?;
+        let mut encoder = image::gif::Encoder::new(file);
+        encoder.encode_frames(frames.into_iter().map(Frame::new))
+    }
+}
+
+fn sort_row(row: &mut [Rgba<u8>], cli: &Cli) {
+    let sort_fn = cli.function.func();
+    let mask_fn = |p: &Rgba<u8>| !(cli.mask_alpha && p.data[3] == 0);
+    let w = row.len();
+
+    let mut ctr = 0;
+    while ctr < w {
+        // find the end of the current "good" sequence
+        let numel = row[ctr..]
+            .iter()
+            .take_while(|p| {
+                let l = sort_fn(p);
+                (l >= u32::from(cli.minimum) && l <= u32::from(cli.maximum)) != cli.invert
+                    && mask_fn(p)
+            })
+            .count();
+
+        // sort
+        row[ctr..ctr + numel].sort_unstable_by(|l, r| {
+            if cli.reverse {
+                sort_fn(r).cmp(&sort_fn(l))
+            } else {
+                sort_fn(l).cmp(&sort_fn(r))
+            }
+        });
+
+        ctr += numel;
+
+        // continue until another value in the right range appears
+        ctr += row[ctr..]
+            .iter()
+            .take_while(|p| {
+                let l = sort_fn(p);
+                (l < u32::from(cli.minimum) || l > u32::from(cli.maximum)) != cli.invert
+                    || !mask_fn(p)
+            })
+            .count();
+    }
+}
+
+fn sort_image(
+    file_in: &Path,
+    cli: &Cli,
+    prog: &ProgressBar,
+    single_input: bool,
+    used_outputs: &mut HashSet<PathBuf>,
+) -> Result<(), ImageError> {
+    prog.set_prefix(&format!(
+        "Sorting {:?} ({}):",
+        file_in,
+        if cli.vertical { "columns" } else { "rows" }
+    ));
+
+    let mut img = image::open(file_in)?;
 
     if cli.vertical {
         img = img.rotate90();
     }
 
     let mut rgba = img.to_rgba();
+
+    if let Some(n) = cli.quantize {
+        rgba = quantize(&rgba, n);
+    }
+
     let (w, h) = rgba.dimensions();
 
-    let prog = ProgressBar::new(h as u64);
+    prog.set_length(h as u64);
     prog.set_draw_delta(h as u64 / 50);
-    prog.set_prefix(&format!(
-        "Sorting {}:",
-        if cli.vertical { "columns" } else { "rows" }
-    ));
-    prog.set_style(ProgressStyle::default_bar().template("{prefix} {wide_bar} {pos:>4}/{len}"));
     prog.tick();
 
-    for (idx_y, row) in rgba
-        .clone()
-        .pixels_mut()
-        .collect::<Vec<_>>()
-        .chunks_mut(w as usize)
-        .enumerate()
-    {
-        let sort_fn = cli.function.func();
-        let mask_fn = |p: &Rgba<u8>| !(cli.mask_alpha && p.data[3] == 0);
-
-        let mut ctr = 0;
-        while ctr < w as usize {
-            // find the end of the current "good" sequence
-            let numel = row[ctr..]
-                .iter()
-                .take_while(|p| {
-                    let l = sort_fn(p);
-                    (l >= cli.minimum && l <= cli.maximum) != cli.invert && mask_fn(p)
-                })
-                .count();
-
-            // sort
-            row[ctr..ctr + numel].sort_unstable_by(|l, r| {
-                if cli.reverse {
-                    sort_fn(r).cmp(&sort_fn(l))
-                } else {
-                    sort_fn(l).cmp(&sort_fn(r))
-                }
-            });
+    let mut pixels: Vec<Rgba<u8>> = rgba.pixels().cloned().collect();
+    let mut frames = Vec::new();
 
-            ctr += numel;
+    if let Some(n_frames) = cli.animate {
+        // Process rows in strict top-down order (no rayon) so the captured
+        // frames are a deterministic, contiguous reveal of the sort instead
+        // of whatever subset of rows happened to finish first.
+        let frame_interval = (u64::from(h) / n_frames.max(1) as u64).max(1);
 
-            // continue until another value in the right range appears
-            ctr += row[ctr..]
-                .iter()
-                .take_while(|p| {
-                    let l = sort_fn(p);
-                    (l < cli.minimum || l > cli.maximum) != cli.invert || !mask_fn(p)
-                })
-                .count();
-        }
+        for (idx_y, row) in pixels.chunks_mut(w as usize).enumerate() {
+            sort_row(row, cli);
+            prog.inc(1);
 
-        for (idx_x, px) in row.iter().enumerate() {
-            rgba.put_pixel(idx_x as u32, idx_y as u32, **px);
+            if (idx_y as u64 + 1) % frame_interval == 0 {
+                let mut snapshot = RgbaImage::new(w, h);
+                for (idx, px) in pixels.iter().enumerate() {
+                    snapshot.put_pixel(idx as u32 % w, idx as u32 / w, *px);
+                }
+                frames.push(snapshot);
+            }
         }
+    } else {
+        let rows_done = AtomicU64::new(0);
+        pixels.par_chunks_mut(w as usize).for_each(|row| {
+            sort_row(row, cli);
+            prog.set_position(rows_done.fetch_add(1, Ordering::Relaxed) + 1);
+        });
+    }
 
-        prog.inc(1);
+    for (idx, px) in pixels.into_iter().enumerate() {
+        rgba.put_pixel(idx as u32 % w, idx as u32 / w, px);
     }
 
     prog.finish_with_message("Done sorting!");
 
+    if cli.animate.is_some() {
+        frames.push(rgba.clone());
+
+        if cli.vertical {
+            frames = frames
+                .into_iter()
+                .map(|f| DynamicImage::ImageRgba8(f).rotate270().to_rgba())
+                .collect();
+        }
+
+        save_animation(frames, file_in, cli, used_outputs)?;
+    }
+
     let mut img_out = DynamicImage::ImageRgba8(rgba);
 
     if cli.vertical {
         img_out = img_out.rotate270();
     }
 
-    let file_out = if let Some(p) = cli.output {
-        p
-    } else {
-        match (
-            cli.file.parent(),
-            cli.file.file_stem(),
-            cli.file.extension(),
-        ) {
-            (None, _, _) | (_, None, _) | (_, _, None) => panic!("Invalid filename"),
-            (Some(p), Some(b), Some(e)) => {
-                let mut fname = b.to_owned();
-                fname.push("_1.");
-                fname.push(e);
-                let mut pth = p.to_owned();
-                pth.push(fname);
-                pth
-            }
-        }
-    };
+    img_out.save(output_path_for(file_in, cli, single_input, used_outputs))?;
+
+    Ok(())
+}
+
+fn main() -> Result<(), ImageError> {
+    let cli = Cli::from_args();
+
+    let files = expand_inputs(&cli.file);
+
+    if files.is_empty() {
+        panic!("No input files found (patterns/directories matched nothing)");
+    }
+
+    if cli.output.is_some() && files.len() > 1 {
+        panic!("-o/--output can only be used with a single input file; use --output-dir instead");
+    }
+
+    let multi = MultiProgress::new();
+    let bars: Vec<ProgressBar> = files
+        .iter()
+        .map(|_| {
+            let bar = multi.add(ProgressBar::new(0));
+            bar.set_style(
+                ProgressStyle::default_bar().template("{prefix} {wide_bar} {pos:>4}/{len}"),
+            );
+            bar
+        })
+        .collect();
+
+    let draw_thread = std::thread::spawn(move || multi.join());
+
+    let single_input = files.len() == 1;
+    let mut used_outputs = HashSet::new();
+    for (file_in, bar) in files.iter().zip(&bars) {
+        sort_image(file_in, &cli, bar, single_input, &mut used_outputs)?;
+    }
 
-    eprintln!("Saving file to {:?}", file_out);
-    img_out.save(file_out)?;
+    draw_thread.join().expect("progress thread panicked")?;
 
     Ok(())
 }